@@ -0,0 +1,223 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::Message;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+// How many (origin, sequence) identities are remembered before the oldest
+// is evicted. Bounded so a long-lived mesh can't grow this without limit.
+const SEEN_CAPACITY: usize = 4096;
+
+// A bounded ring of recently forwarded message identities, so a message
+// already relayed around the mesh is recognised and dropped rather than
+// propagated again.
+struct SeenSet {
+    order: VecDeque<(String, u64)>,
+    seen: HashSet<(String, u64)>,
+}
+
+impl SeenSet {
+    fn new() -> SeenSet {
+        SeenSet {
+            order: VecDeque::new(),
+            seen: HashSet::new(),
+        }
+    }
+
+    // Records `(origin, sequence)`, returning `true` the first time it is
+    // seen and `false` on every subsequent sighting.
+    fn record(&mut self, origin: &str, sequence: u64) -> bool {
+        let identity = (origin.to_string(), sequence);
+        if self.seen.contains(&identity) {
+            return false;
+        }
+
+        if self.order.len() >= SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+
+        self.order.push_back(identity.clone());
+        self.seen.insert(identity);
+        true
+    }
+}
+
+// Tracks this server's linked peers and relays messages between them,
+// stamping each with an origin server identifier and a monotonic sequence
+// number in its `prefix` (`<server name>:<sequence>`) so a bounded seen-set
+// can recognise and drop a message already forwarded, preventing it from
+// echoing forever around a mesh of links.
+//
+// Each peer is represented only by a channel to its connection's owning
+// thread, never by the connection itself: the owning thread alone performs
+// blocking reads and writes on its socket, so a relay from another thread
+// never has to wait on a lock held for the duration of an idle peer's read.
+pub struct Links {
+    server_name: String,
+    link_password: String,
+    sequence: AtomicU64,
+    peers: Mutex<HashMap<String, Sender<Message>>>,
+    seen: Mutex<SeenSet>,
+}
+
+impl Links {
+    pub fn new(server_name: String, link_password: String) -> Links {
+        Links {
+            server_name,
+            link_password,
+            sequence: AtomicU64::new(0),
+            peers: Mutex::new(HashMap::new()),
+            seen: Mutex::new(SeenSet::new()),
+        }
+    }
+
+    // Requires a non-empty configured password, so a server with linking
+    // left unconfigured (the default) can never be linked into by anyone,
+    // rather than accepting an empty password from any peer that sends one.
+    pub fn authenticate(&self, password: Option<&str>) -> bool {
+        if self.link_password.is_empty() {
+            return false;
+        }
+        password.is_some_and(|password| password == self.link_password)
+    }
+
+    pub fn register(&self, server_name: String, sender: Sender<Message>) {
+        self.peers.lock().unwrap().insert(server_name, sender);
+    }
+
+    // Builds this server's own PASS/SERVER handshake pair, so it can be
+    // presented back to a peer that just linked in (or that this server is
+    // linking out to), making the handshake reciprocal regardless of which
+    // side dialed the connection.
+    pub fn greeting(&self) -> (Message, Message) {
+        let mut pass = Message::new();
+        pass.set_command("PASS");
+        pass.add_parameter(&self.link_password);
+
+        let mut server = Message::new();
+        server.set_command("SERVER");
+        server.add_parameter(&self.server_name);
+
+        (pass, server)
+    }
+
+    // Stamps a locally-originated message with this server's identity and
+    // relays it to every linked peer.
+    pub fn originate(&self, mut message: Message) {
+        let sequence = self.sequence.fetch_add(1, Ordering::Relaxed);
+        message.set_prefix(&format!("{}:{}", self.server_name, sequence));
+        self.seen.lock().unwrap().record(&self.server_name, sequence);
+        self.broadcast(&message, None);
+    }
+
+    // Relays a message received from the peer linked in as `from`. Drops
+    // it if its (origin, sequence) identity has already been forwarded,
+    // and never forwards it back to `from`, guaranteeing single delivery
+    // across an arbitrary link topology.
+    pub fn relay(&self, message: &Message, from: &str) {
+        let (origin, sequence) = match parse_identity(message.prefix()) {
+            Some(identity) => identity,
+            None => return,
+        };
+
+        if !self.seen.lock().unwrap().record(&origin, sequence) {
+            return;
+        }
+
+        self.broadcast(message, Some(from));
+    }
+
+    // Forwards `message` to a single named peer, e.g. for a rule's
+    // `Redirect` action. Returns `false` if no peer is linked under that
+    // name or its connection has gone away. Queuing onto the peer's channel
+    // never blocks on that peer's socket, so a stalled or idle link cannot
+    // stall the caller.
+    pub fn send_to(&self, server_name: &str, message: &Message) -> bool {
+        match self.peers.lock().unwrap().get(server_name) {
+            Some(sender) => sender.send(message.clone()).is_ok(),
+            None => false,
+        }
+    }
+
+    fn broadcast(&self, message: &Message, except: Option<&str>) {
+        for (server_name, sender) in self.peers.lock().unwrap().iter() {
+            if except == Some(server_name.as_str()) {
+                continue;
+            }
+            let _ = sender.send(message.clone());
+        }
+    }
+}
+
+fn parse_identity(prefix: &str) -> Option<(String, u64)> {
+    let mut parts = prefix.rsplitn(2, ':');
+    let sequence = parts.next()?.parse::<u64>().ok()?;
+    let origin = parts.next()?.to_string();
+    Some((origin, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_an_identity_once() {
+        let mut seen = SeenSet::new();
+        assert!(seen.record("a", 1));
+        assert!(!seen.record("a", 1));
+    }
+
+    #[test]
+    fn distinguishes_origins_and_sequences() {
+        let mut seen = SeenSet::new();
+        assert!(seen.record("a", 1));
+        assert!(seen.record("b", 1));
+        assert!(seen.record("a", 2));
+    }
+
+    #[test]
+    fn evicts_the_oldest_identity_once_full() {
+        let mut seen = SeenSet::new();
+        for sequence in 0..SEEN_CAPACITY as u64 {
+            assert!(seen.record("origin", sequence));
+        }
+
+        // The ring is full, so recording one more evicts (origin, 0),
+        // letting it be seen again.
+        assert!(seen.record("origin", SEEN_CAPACITY as u64));
+        assert!(seen.record("origin", 0));
+    }
+
+    #[test]
+    fn parses_a_well_formed_identity() {
+        assert_eq!(
+            parse_identity("server1:42"),
+            Some(("server1".to_string(), 42))
+        );
+    }
+
+    #[test]
+    fn rejects_a_malformed_identity() {
+        assert_eq!(parse_identity("no-sequence-here"), None);
+        assert_eq!(parse_identity(""), None);
+    }
+}
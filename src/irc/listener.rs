@@ -0,0 +1,162 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::{Connection, Message, Stream, TlsConfig};
+use rustls::internal::pemfile;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+#[derive(Clone)]
+pub struct Listener {
+    bind_string: String,
+    tls_config: Option<TlsConfig>,
+    request_queue: Arc<Mutex<VecDeque<Connection>>>,
+}
+
+impl Listener {
+    pub fn clone_request_queue(&self) -> Arc<Mutex<VecDeque<Connection>>> {
+        self.request_queue.clone()
+    }
+
+    pub fn set_bind_string(&mut self, bind_string: String) {
+        self.bind_string = bind_string;
+    }
+
+    pub fn set_tls_config(&mut self, tls_config: TlsConfig) {
+        self.tls_config = Some(tls_config);
+    }
+
+    // Binds the plaintext socket and, if a `TlsConfig` was set, the TLS
+    // socket, each accepting connections on its own thread and pushing
+    // them onto the shared request queue. The handshake itself is chosen
+    // per accept, by which socket handed us the connection.
+    pub fn run(&mut self) -> Vec<JoinHandle<()>> {
+        let mut handles = vec![self.run_plain()];
+
+        if let Some(tls_config) = self.tls_config.clone() {
+            handles.push(self.run_tls(tls_config));
+        }
+
+        handles
+    }
+
+    fn run_plain(&self) -> JoinHandle<()> {
+        let bind_string = self.bind_string.clone();
+        let request_queue = self.request_queue.clone();
+
+        thread::spawn(move || {
+            let tcp_listener = match TcpListener::bind(&bind_string) {
+                Ok(tcp_listener) => tcp_listener,
+                Err(_e) => return,
+            };
+
+            for stream in tcp_listener.incoming().flatten() {
+                request_queue
+                    .lock()
+                    .unwrap()
+                    .push_back(Connection::new(Stream::Plain(stream)));
+            }
+        })
+    }
+
+    fn run_tls(&self, tls_config: TlsConfig) -> JoinHandle<()> {
+        let request_queue = self.request_queue.clone();
+
+        thread::spawn(move || {
+            let server_config = match build_server_config(&tls_config) {
+                Ok(server_config) => Arc::new(server_config),
+                Err(_e) => return,
+            };
+
+            let tcp_listener = match TcpListener::bind(tls_config.bind_string()) {
+                Ok(tcp_listener) => tcp_listener,
+                Err(_e) => return,
+            };
+
+            for stream in tcp_listener.incoming() {
+                let tcp_stream = match stream {
+                    Ok(tcp_stream) => tcp_stream,
+                    Err(_e) => continue,
+                };
+
+                let session = rustls::ServerSession::new(&server_config);
+                let tls_stream = rustls::StreamOwned::new(session, tcp_stream);
+                request_queue
+                    .lock()
+                    .unwrap()
+                    .push_back(Connection::new(Stream::Tls(Box::new(tls_stream))));
+            }
+        })
+    }
+
+    // Actively connects out to a peer server, performs the PASS/SERVER
+    // handshake, and hands the resulting connection to the same request
+    // queue an accepted connection would land on, so it is serviced by
+    // the ordinary worker pool.
+    pub fn link(&self, address: &str, password: &str, server_name: &str) -> io::Result<()> {
+        let mut stream = Stream::Plain(TcpStream::connect(address)?);
+
+        let mut pass = Message::new();
+        pass.set_command("PASS");
+        pass.add_parameter(password);
+        stream.write_all(pass.string().as_bytes())?;
+
+        let mut server = Message::new();
+        server.set_command("SERVER");
+        server.add_parameter(server_name);
+        stream.write_all(server.string().as_bytes())?;
+
+        self.request_queue
+            .lock()
+            .unwrap()
+            .push_back(Connection::new(stream));
+
+        Ok(())
+    }
+
+    pub fn new() -> Listener {
+        Listener {
+            bind_string: String::new(),
+            tls_config: None,
+            request_queue: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+}
+
+fn build_server_config(tls_config: &TlsConfig) -> io::Result<rustls::ServerConfig> {
+    let mut certificate_reader = BufReader::new(File::open(tls_config.certificate_path())?);
+    let certificate_chain = pemfile::certs(&mut certificate_reader)
+        .map_err(|_e| io::Error::new(ErrorKind::InvalidData, "invalid certificate chain"))?;
+
+    let mut key_reader = BufReader::new(File::open(tls_config.private_key_path())?);
+    let private_key = pemfile::pkcs8_private_keys(&mut key_reader)
+        .map_err(|_e| io::Error::new(ErrorKind::InvalidData, "invalid private key"))?
+        .pop()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidData, "no private key found"))?;
+
+    let mut server_config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+    server_config
+        .set_single_cert(certificate_chain, private_key)
+        .map_err(|_e| io::Error::new(ErrorKind::InvalidData, "certificate does not match private key"))?;
+
+    Ok(server_config)
+}
@@ -15,41 +15,51 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Platform.  If not, see <https://www.gnu.org/licenses/>.
 
-use crate::irc::BUFFER_SIZE;
-use std::io::ErrorKind;
-use std::net::TcpStream;
+use crate::irc::{Stream, BUFFER_SIZE};
+use std::io::{self, ErrorKind};
 use std::net::{IpAddr, Ipv4Addr};
 use std::ops::Add;
-use std::str::from_utf8;
+use std::time::Duration;
 
 pub struct Connection {
-    tcp_stream: TcpStream,
+    stream: Stream,
 }
 
 impl Connection {
     pub fn id(&self) -> String {
-        let ip = match self.tcp_stream.peer_addr() {
+        let ip = match self.stream.peer_addr() {
             Ok(addr) => addr.ip(),
             Err(_e) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
         };
-        let port = match self.tcp_stream.peer_addr() {
+        let port = match self.stream.peer_addr() {
             Ok(addr) => addr.port(),
             Err(_e) => 0,
         };
         format!("{:}:{:}", ip, port)
     }
 
-    pub fn stream(&self) -> &TcpStream {
-        &self.tcp_stream
+    pub fn is_encrypted(&self) -> bool {
+        self.stream.is_encrypted()
     }
 
-    pub fn new(tcp_stream: TcpStream) -> Connection {
-        Connection {
-            tcp_stream: tcp_stream,
-        }
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    pub fn stream_mut(&mut self) -> &mut Stream {
+        &mut self.stream
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    pub fn new(stream: Stream) -> Connection {
+        Connection { stream }
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
 pub struct Message {
     command: String,
     parameters: Vec<String>,
@@ -69,6 +79,10 @@ impl Message {
         &self.parameters
     }
 
+    pub fn prefix(&self) -> &String {
+        &self.prefix
+    }
+
     pub fn set_command(&mut self, command: &str) {
         self.command = command.to_string();
     }
@@ -80,19 +94,19 @@ impl Message {
     pub fn string(&self) -> String {
         let mut string = String::new();
         if !self.prefix.is_empty() {
-            string.push_str(":");
+            string.push(':');
             string.push_str(&self.prefix);
-            string.push_str(" ");
+            string.push(' ');
         }
         string.push_str(&self.command);
         for p in &self.parameters {
-            string.push_str(" ");
-            if p.contains(" ") {
-                string.push_str(":");
-                string.push_str(&p);
+            string.push(' ');
+            if p.contains(' ') {
+                string.push(':');
+                string.push_str(p);
                 break;
             }
-            string.push_str(&p);
+            string.push_str(p);
         }
         string.push_str("\r\n");
         string
@@ -117,12 +131,12 @@ impl Message {
                 command = p.to_string();
             } else if !last_parameter.is_empty() {
                 last_parameter.push_str(p);
-                last_parameter.push_str(" ");
+                last_parameter.push(' ');
             } else if p.chars().nth(0) == Some(':') {
                 let mut p = p.to_string();
                 p.remove(0);
                 last_parameter.push_str(&p);
-                last_parameter.push_str(" ");
+                last_parameter.push(' ');
             } else {
                 parameters.push(p.to_string());
             }
@@ -134,9 +148,9 @@ impl Message {
         }
 
         Message {
-            command: command,
-            parameters: parameters,
-            prefix: prefix,
+            command,
+            parameters,
+            prefix,
         }
     }
 
@@ -171,10 +185,10 @@ impl Reply {
         let mut buffer = String::new();
         for message in &self.messages {
             let string = message.string();
-            if string.as_bytes().len() > BUFFER_SIZE {
+            if string.len() > BUFFER_SIZE {
                 return Err(ErrorKind::InvalidData);
             }
-            if buffer.as_bytes().len() + string.as_bytes().len() <= BUFFER_SIZE {
+            if buffer.len() + string.len() <= BUFFER_SIZE {
                 buffer.push_str(&string);
             } else {
                 data.push(buffer);
@@ -203,75 +217,3 @@ impl Add for Reply {
         reply
     }
 }
-
-pub struct Request {
-    data: [u8; BUFFER_SIZE],
-    messages: Vec<Message>,
-    size: usize,
-}
-
-impl Request {
-    pub fn clear_data(&mut self) {
-        self.data = [0 as u8; BUFFER_SIZE];
-        self.messages.clear();
-        self.size = 0;
-    }
-
-    pub fn data(&mut self) -> &mut [u8; BUFFER_SIZE] {
-        &mut self.data
-    }
-
-    pub fn messages(&mut self) -> &Vec<Message> {
-        if self.messages.is_empty() {
-            for message in self.string().split("\r\n") {
-                if message != "" {
-                    self.messages
-                        .push(Message::from_string(message.to_string()));
-                }
-            }
-        }
-        &self.messages
-    }
-
-    pub fn size(&mut self) -> usize {
-        let mut size: usize = 0;
-
-        if self.size == 0 {
-            for c in &self.data[..] {
-                if *c == 0 {
-                    break;
-                }
-                size = size + 1;
-            }
-
-            self.size = size;
-        }
-
-        self.size
-    }
-
-    pub fn string(&mut self) -> String {
-        let size = self.size();
-        match from_utf8(&self.data()[..size]) {
-            Ok(s) => s.to_string(),
-            Err(_e) => "".to_string(),
-        }
-    }
-
-    pub fn valid(&mut self) -> bool {
-        let size = self.size();
-        if size > 2 && self.data[size - 1] == b'\n' && self.data[size - 2] == b'\r' {
-            true
-        } else {
-            false
-        }
-    }
-
-    pub fn new() -> Request {
-        Request {
-            data: [0 as u8; BUFFER_SIZE],
-            messages: Vec::new(),
-            size: 0,
-        }
-    }
-}
@@ -0,0 +1,202 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::Message;
+use std::io::ErrorKind;
+
+// Caps how long a single line may be before it is rejected, shared by any
+// type that parses directly off the wire.
+pub trait LineLimit {
+    const MAX_LINE: usize = 512;
+
+    fn validate_len(len: usize) -> Result<(), ErrorKind> {
+        if len > Self::MAX_LINE {
+            Err(ErrorKind::InvalidData)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+enum Field {
+    First,
+    AfterPrefix,
+    Parameters,
+}
+
+// A borrowed view of a single IRC line, parsed in one pass without copying
+// any text: `prefix`/`command` are slices into the source line, and each
+// parameter is stored as a byte range rather than a copied `String`. The
+// only allocation is the `Vec` holding those ranges.
+// Call `to_owned` when the parsed view needs to outlive the buffer it
+// borrows from.
+pub struct MessageRef<'a> {
+    source: &'a str,
+    prefix: &'a str,
+    command: &'a str,
+    parameters: Vec<(usize, usize)>,
+}
+
+impl<'a> LineLimit for MessageRef<'a> {}
+
+impl<'a> MessageRef<'a> {
+    pub fn command(&self) -> &'a str {
+        self.command
+    }
+
+    pub fn prefix(&self) -> &'a str {
+        self.prefix
+    }
+
+    pub fn parameter(&self, index: usize) -> Option<&'a str> {
+        self.parameters
+            .get(index)
+            .map(|&(start, end)| &self.source[start..end])
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameters.len()
+    }
+
+    pub fn to_owned(&self) -> Message {
+        let mut message = Message::new();
+        message.set_prefix(self.prefix);
+        message.set_command(self.command);
+        for index in 0..self.parameter_count() {
+            if let Some(parameter) = self.parameter(index) {
+                message.add_parameter(parameter);
+            }
+        }
+        message
+    }
+
+    // Parses `source` without copying it: `prefix`/`command` borrow directly
+    // from it, and parameters are recorded as byte ranges. The trailing
+    // `:`-prefixed parameter, which may itself contain spaces, runs to the
+    // end of the line rather than being split further.
+    pub fn from_str(source: &'a str) -> Result<MessageRef<'a>, ErrorKind> {
+        let bytes = source.as_bytes();
+        Self::validate_len(bytes.len())?;
+
+        let mut prefix = "";
+        let mut command = "";
+        let mut parameters = Vec::new();
+        let mut field = Field::First;
+        let mut pos = 0;
+        let len = bytes.len();
+
+        while pos < len {
+            while pos < len && bytes[pos] == b' ' {
+                pos += 1;
+            }
+            if pos >= len {
+                break;
+            }
+
+            match field {
+                Field::First if bytes[pos] == b':' => {
+                    let end = next_space(bytes, pos + 1);
+                    prefix = &source[pos + 1..end];
+                    pos = end;
+                    field = Field::AfterPrefix;
+                }
+                Field::First | Field::AfterPrefix => {
+                    let end = next_space(bytes, pos);
+                    command = &source[pos..end];
+                    pos = end;
+                    field = Field::Parameters;
+                }
+                Field::Parameters if bytes[pos] == b':' => {
+                    parameters.push((pos + 1, len));
+                    break;
+                }
+                Field::Parameters => {
+                    let end = next_space(bytes, pos);
+                    parameters.push((pos, end));
+                    pos = end;
+                }
+            }
+        }
+
+        Ok(MessageRef {
+            source,
+            prefix,
+            command,
+            parameters,
+        })
+    }
+}
+
+fn next_space(bytes: &[u8], start: usize) -> usize {
+    let mut pos = start;
+    while pos < bytes.len() && bytes[pos] != b' ' {
+        pos += 1;
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_and_parameters_without_a_prefix() {
+        let message_ref = MessageRef::from_str("JOIN #rust :hello there").unwrap();
+        assert_eq!(message_ref.prefix(), "");
+        assert_eq!(message_ref.command(), "JOIN");
+        assert_eq!(message_ref.parameter(0), Some("#rust"));
+        assert_eq!(message_ref.parameter(1), Some("hello there"));
+        assert_eq!(message_ref.parameter_count(), 2);
+    }
+
+    #[test]
+    fn parses_a_prefix_when_present() {
+        let message_ref = MessageRef::from_str(":nick!user@host PRIVMSG #rust :hi").unwrap();
+        assert_eq!(message_ref.prefix(), "nick!user@host");
+        assert_eq!(message_ref.command(), "PRIVMSG");
+        assert_eq!(message_ref.parameter(0), Some("#rust"));
+        assert_eq!(message_ref.parameter(1), Some("hi"));
+    }
+
+    #[test]
+    fn trailing_parameter_may_contain_spaces() {
+        let message_ref = MessageRef::from_str("PRIVMSG #rust :a longer message here").unwrap();
+        assert_eq!(message_ref.parameter(0), Some("#rust"));
+        assert_eq!(message_ref.parameter(1), Some("a longer message here"));
+    }
+
+    #[test]
+    fn missing_parameter_is_none() {
+        let message_ref = MessageRef::from_str("PING").unwrap();
+        assert_eq!(message_ref.parameter(0), None);
+    }
+
+    #[test]
+    fn rejects_a_line_over_the_limit() {
+        let oversized = "a".repeat(MessageRef::MAX_LINE + 1);
+        assert!(MessageRef::from_str(&oversized).is_err());
+    }
+
+    #[test]
+    fn to_owned_round_trips_into_a_message() {
+        let message_ref = MessageRef::from_str(":nick PRIVMSG #rust :hi").unwrap();
+        let message = message_ref.to_owned();
+        assert_eq!(message.prefix(), "nick");
+        assert_eq!(message.command(), "PRIVMSG");
+        assert_eq!(message.parameters(), &vec!["#rust".to_string(), "hi".to_string()]);
+    }
+}
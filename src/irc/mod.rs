@@ -0,0 +1,38 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+mod link;
+mod listener;
+mod message;
+mod message_ref;
+mod rules;
+mod service;
+mod stream;
+mod transport;
+mod worker;
+
+pub use link::Links;
+pub use listener::Listener;
+pub use message::{Connection, Message, Reply};
+pub use message_ref::MessageRef;
+pub use rules::Ruleset;
+pub use service::Service;
+pub use stream::MessageStream;
+pub use transport::{Stream, TlsConfig};
+pub use worker::Worker;
+
+pub const BUFFER_SIZE: usize = 512;
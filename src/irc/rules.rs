@@ -0,0 +1,494 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::{Message, MessageRef};
+
+// A common view over a parsed message, implemented by both the owned
+// `Message` and the borrowed `MessageRef`, so rule conditions can match
+// directly against whichever one is on hand without forcing an allocation
+// just to evaluate a condition.
+pub trait MessageLike {
+    fn command(&self) -> &str;
+    fn prefix(&self) -> &str;
+    fn parameter(&self, index: usize) -> Option<&str>;
+}
+
+impl MessageLike for Message {
+    fn command(&self) -> &str {
+        self.command().as_str()
+    }
+
+    fn prefix(&self) -> &str {
+        self.prefix().as_str()
+    }
+
+    fn parameter(&self, index: usize) -> Option<&str> {
+        self.parameters().get(index).map(String::as_str)
+    }
+}
+
+impl<'a> MessageLike for MessageRef<'a> {
+    fn command(&self) -> &str {
+        MessageRef::command(self)
+    }
+
+    fn prefix(&self) -> &str {
+        MessageRef::prefix(self)
+    }
+
+    fn parameter(&self, index: usize) -> Option<&str> {
+        MessageRef::parameter(self, index)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Pattern {
+    Substring(String),
+    Glob(String),
+    Regex(String),
+}
+
+impl Pattern {
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Substring(needle) => value.contains(needle.as_str()),
+            Pattern::Glob(pattern) => glob_matches(pattern.as_bytes(), value.as_bytes()),
+            Pattern::Regex(pattern) => regex_matches(pattern.as_bytes(), value.as_bytes()),
+        }
+    }
+}
+
+// `*` matches any run of characters, `?` matches exactly one. Iterative
+// (no recursion, no backtracking): tracks the most recent `*` seen and how
+// much of `value` it has already consumed, backing off one byte at a time
+// only on a later mismatch, giving O(pattern.len() * value.len()) worst
+// case instead of the exponential blowup a naive recursive matcher hits on
+// a pattern with several stars against a long non-matching value.
+fn glob_matches(pattern: &[u8], value: &[u8]) -> bool {
+    let (mut pi, mut vi) = (0, 0);
+    let (mut star_pi, mut star_vi) = (None, 0);
+
+    while vi < value.len() {
+        if pi < pattern.len() && (pattern[pi] == b'?' || pattern[pi] == value[vi]) {
+            pi += 1;
+            vi += 1;
+        } else if pi < pattern.len() && pattern[pi] == b'*' {
+            star_pi = Some(pi);
+            star_vi = vi;
+            pi += 1;
+        } else if let Some(last_star) = star_pi {
+            pi = last_star + 1;
+            star_vi += 1;
+            vi = star_vi;
+        } else {
+            return false;
+        }
+    }
+
+    while pi < pattern.len() && pattern[pi] == b'*' {
+        pi += 1;
+    }
+
+    pi == pattern.len()
+}
+
+// `.` matches any single character, `*` repeats the preceding atom zero or
+// more times; there are no escapes, so every other byte (including `\`)
+// matches itself. Deliberately small: enough to spot patterns like
+// `.*@spam.net` without pulling in a regex crate. Runs a Thompson-style NFA simulation
+// (the set of pattern positions reachable after each input byte) instead of
+// recursive backtracking, so it stays O(pattern.len() * value.len()) even
+// against a long, non-matching, attacker-controlled value.
+fn regex_matches(pattern: &[u8], value: &[u8]) -> bool {
+    let mut current: Vec<usize> = Vec::new();
+    add_state(pattern, 0, &mut current);
+
+    for &byte in value {
+        let mut next = Vec::new();
+        for &state in &current {
+            if state < pattern.len() {
+                let atom_matches = pattern[state] == byte || pattern[state] == b'.';
+                let repeats = pattern.get(state + 1) == Some(&b'*');
+                if atom_matches {
+                    add_state(pattern, if repeats { state } else { state + 1 }, &mut next);
+                }
+            }
+        }
+        current = next;
+        if current.is_empty() {
+            return false;
+        }
+    }
+
+    current.contains(&pattern.len())
+}
+
+// Adds pattern position `pos` to `states`, following every `*`-quantified
+// atom's "skip it" edge so the set always holds every position reachable
+// without consuming another byte of input.
+fn add_state(pattern: &[u8], pos: usize, states: &mut Vec<usize>) {
+    if states.contains(&pos) {
+        return;
+    }
+    states.push(pos);
+    if pattern.get(pos + 1) == Some(&b'*') {
+        add_state(pattern, pos + 2, states);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Command(String),
+    Prefix(Pattern),
+    Parameter(usize, Pattern),
+}
+
+impl Condition {
+    pub fn matches<M: MessageLike>(&self, message: &M) -> bool {
+        match self {
+            Condition::Command(command) => message.command() == command,
+            Condition::Prefix(pattern) => pattern.matches(message.prefix()),
+            Condition::Parameter(index, pattern) => message
+                .parameter(*index)
+                .is_some_and(|parameter| pattern.matches(parameter)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rewrite {
+    Command(String),
+    Prefix(String),
+    AddParameter(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Drop,
+    Reply(Message),
+    Rewrite(Rewrite),
+    Redirect(String),
+    Stop,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    condition: Condition,
+    actions: Vec<Action>,
+}
+
+pub struct Outcome {
+    // `None` only when the message was dropped and never redirected, so it
+    // never needed to be materialized out of the borrowed `MessageRef` the
+    // ruleset was applied to.
+    pub message: Option<Message>,
+    pub keep: bool,
+    pub replies: Vec<Message>,
+    pub redirects: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ruleset {
+    rules: Vec<Rule>,
+}
+
+impl Ruleset {
+    pub fn new() -> Ruleset {
+        Ruleset { rules: Vec::new() }
+    }
+
+    // Evaluates every rule top-to-bottom against `message_ref`, applying
+    // each matching rule's actions in order. A `stop` action halts
+    // evaluation early; reaching the end of the ruleset without one is an
+    // implicit "keep". `message_ref` is matched against directly, with no
+    // allocation, until a rule actually rewrites it: only then is it
+    // materialized into an owned `Message`, which every later condition
+    // then matches against instead so it sees the earlier rewrite.
+    pub fn apply(&self, message_ref: &MessageRef) -> Outcome {
+        let mut owned: Option<Message> = None;
+        let mut keep = true;
+        let mut replies = Vec::new();
+        let mut redirects = Vec::new();
+
+        for rule in &self.rules {
+            let matches = match &owned {
+                Some(message) => rule.condition.matches(message),
+                None => rule.condition.matches(message_ref),
+            };
+            if !matches {
+                continue;
+            }
+
+            let mut stop = false;
+            for action in &rule.actions {
+                match action {
+                    Action::Drop => keep = false,
+                    Action::Reply(reply) => replies.push(reply.clone()),
+                    Action::Rewrite(rewrite) => {
+                        let message = owned.get_or_insert_with(|| message_ref.to_owned());
+                        match rewrite {
+                            Rewrite::Command(command) => message.set_command(command),
+                            Rewrite::Prefix(prefix) => message.set_prefix(prefix),
+                            Rewrite::AddParameter(parameter) => message.add_parameter(parameter),
+                        }
+                    }
+                    Action::Redirect(target) => redirects.push(target.clone()),
+                    Action::Stop => stop = true,
+                }
+            }
+
+            if stop {
+                break;
+            }
+        }
+
+        if owned.is_none() && (keep || !redirects.is_empty()) {
+            owned = Some(message_ref.to_owned());
+        }
+
+        Outcome {
+            message: owned,
+            keep,
+            replies,
+            redirects,
+        }
+    }
+
+    // Parses a ruleset from its text form:
+    //
+    //   rule drop-spam
+    //   when parameter 0 contains viagra
+    //   drop
+    //   stop
+    //   end
+    //
+    // Each `rule ... end` block holds exactly one `when <condition>` line
+    // and any number of action lines (`drop`, `stop`, `redirect <target>`,
+    // `reply <raw message>`, `rewrite command|prefix|add_parameter <value>`).
+    // Blank lines and `#`-prefixed comments are ignored.
+    pub fn parse(script: &str) -> Result<Ruleset, String> {
+        let mut lines = script
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        let mut rules = Vec::new();
+
+        while let Some(line) = lines.next() {
+            let mut tokens = line.splitn(2, ' ');
+            if tokens.next() != Some("rule") {
+                return Err(format!("expected 'rule', found: {}", line));
+            }
+
+            let mut condition = None;
+            let mut actions = Vec::new();
+
+            loop {
+                let line = lines.next().ok_or("unterminated rule block")?;
+                if line == "end" {
+                    break;
+                } else if let Some(rest) = line.strip_prefix("when ") {
+                    if condition.is_some() {
+                        return Err("rule has more than one condition".to_string());
+                    }
+                    condition = Some(parse_condition(rest)?);
+                } else {
+                    actions.push(parse_action(line)?);
+                }
+            }
+
+            rules.push(Rule {
+                condition: condition.ok_or("rule has no condition")?,
+                actions,
+            });
+        }
+
+        Ok(Ruleset { rules })
+    }
+}
+
+fn parse_pattern(text: &str) -> Result<Pattern, String> {
+    let mut tokens = text.splitn(2, ' ');
+    let kind = tokens.next().ok_or("pattern has no kind")?;
+    let value = tokens.next().ok_or("pattern has no value")?.to_string();
+    match kind {
+        "contains" => Ok(Pattern::Substring(value)),
+        "glob" => Ok(Pattern::Glob(value)),
+        "regex" => Ok(Pattern::Regex(value)),
+        other => Err(format!("unknown pattern kind: {}", other)),
+    }
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let mut tokens = text.splitn(2, ' ');
+    match tokens.next() {
+        Some("command") => Ok(Condition::Command(
+            tokens.next().ok_or("command condition has no value")?.to_string(),
+        )),
+        Some("prefix") => {
+            let rest = tokens.next().ok_or("prefix condition has no pattern")?;
+            Ok(Condition::Prefix(parse_pattern(rest)?))
+        }
+        Some("parameter") => {
+            let rest = tokens.next().ok_or("parameter condition has no index")?;
+            let mut rest = rest.splitn(2, ' ');
+            let index = rest
+                .next()
+                .ok_or("parameter condition has no index")?
+                .parse::<usize>()
+                .map_err(|e| e.to_string())?;
+            let pattern = rest.next().ok_or("parameter condition has no pattern")?;
+            Ok(Condition::Parameter(index, parse_pattern(pattern)?))
+        }
+        Some(other) => Err(format!("unknown condition: {}", other)),
+        None => Err("empty condition".to_string()),
+    }
+}
+
+fn parse_action(line: &str) -> Result<Action, String> {
+    let mut tokens = line.splitn(2, ' ');
+    match tokens.next() {
+        Some("drop") => Ok(Action::Drop),
+        Some("stop") => Ok(Action::Stop),
+        Some("reply") => {
+            let text = tokens.next().ok_or("reply action has no message")?;
+            Ok(Action::Reply(Message::from_string(text.to_string())))
+        }
+        Some("redirect") => {
+            let target = tokens.next().ok_or("redirect action has no target")?;
+            Ok(Action::Redirect(target.to_string()))
+        }
+        Some("rewrite") => {
+            let rest = tokens.next().ok_or("rewrite action has no field")?;
+            let mut rest = rest.splitn(2, ' ');
+            match rest.next() {
+                Some("command") => Ok(Action::Rewrite(Rewrite::Command(
+                    rest.next().ok_or("rewrite command has no value")?.to_string(),
+                ))),
+                Some("prefix") => Ok(Action::Rewrite(Rewrite::Prefix(
+                    rest.next().ok_or("rewrite prefix has no value")?.to_string(),
+                ))),
+                Some("add_parameter") => Ok(Action::Rewrite(Rewrite::AddParameter(
+                    rest.next()
+                        .ok_or("rewrite add_parameter has no value")?
+                        .to_string(),
+                ))),
+                Some(other) => Err(format!("unknown rewrite field: {}", other)),
+                None => Err("rewrite action has no field".to_string()),
+            }
+        }
+        Some(other) => Err(format!("unknown action: {}", other)),
+        None => Err("empty action".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matches_a_single_star() {
+        assert!(glob_matches(b"foo*", b"foobar"));
+        assert!(!glob_matches(b"foo*", b"barfoo"));
+    }
+
+    #[test]
+    fn glob_matches_several_stars() {
+        assert!(glob_matches(b"*foo*bar*", b"xxfooyybarzz"));
+        assert!(!glob_matches(b"*foo*bar*", b"xxfooyybazzz"));
+    }
+
+    #[test]
+    fn glob_matches_question_mark() {
+        assert!(glob_matches(b"f?o", b"foo"));
+        assert!(!glob_matches(b"f?o", b"fo"));
+    }
+
+    #[test]
+    fn glob_does_not_hang_on_a_pathological_pattern() {
+        let pattern = "*a".repeat(20) + "b";
+        let value = "a".repeat(40);
+        assert!(!glob_matches(pattern.as_bytes(), value.as_bytes()));
+    }
+
+    #[test]
+    fn regex_matches_dot_and_star() {
+        assert!(regex_matches(b".*@spam", b"user@spam"));
+        assert!(!regex_matches(b".*@spam", b"user@ham"));
+    }
+
+    #[test]
+    fn regex_does_not_hang_on_a_pathological_pattern() {
+        let pattern = "a*".repeat(20);
+        let value = "a".repeat(40) + "b";
+        assert!(!regex_matches(pattern.as_bytes(), value.as_bytes()));
+    }
+
+    fn ruleset(script: &str) -> Ruleset {
+        Ruleset::parse(script).unwrap()
+    }
+
+    #[test]
+    fn drop_discards_a_matching_message() {
+        let ruleset = ruleset("rule drop-pings\nwhen command PING\ndrop\nend\n");
+        let message_ref = MessageRef::from_str("PING :1").unwrap();
+        let outcome = ruleset.apply(&message_ref);
+        assert!(!outcome.keep);
+        assert!(outcome.message.is_none());
+    }
+
+    #[test]
+    fn rewrite_materializes_and_mutates_the_message() {
+        let ruleset = ruleset(
+            "rule rewrite-join\nwhen command JOIN\nrewrite command PART\nend\n",
+        );
+        let message_ref = MessageRef::from_str("JOIN #rust").unwrap();
+        let outcome = ruleset.apply(&message_ref);
+        assert!(outcome.keep);
+        assert_eq!(outcome.message.unwrap().command(), "PART");
+    }
+
+    #[test]
+    fn non_matching_rule_leaves_the_message_untouched() {
+        let ruleset = ruleset("rule drop-join\nwhen command JOIN\ndrop\nend\n");
+        let message_ref = MessageRef::from_str("PING :1").unwrap();
+        let outcome = ruleset.apply(&message_ref);
+        assert!(outcome.keep);
+        assert_eq!(outcome.message.unwrap().command(), "PING");
+    }
+
+    #[test]
+    fn stop_halts_evaluation_of_later_rules() {
+        let ruleset = ruleset(
+            "rule first\nwhen command PING\nstop\nend\n\
+             rule second\nwhen command PING\ndrop\nend\n",
+        );
+        let message_ref = MessageRef::from_str("PING :1").unwrap();
+        let outcome = ruleset.apply(&message_ref);
+        assert!(outcome.keep);
+    }
+
+    #[test]
+    fn redirect_collects_its_target() {
+        let ruleset = ruleset(
+            "rule redirect-ping\nwhen command PING\nredirect elsewhere\nend\n",
+        );
+        let message_ref = MessageRef::from_str("PING :1").unwrap();
+        let outcome = ruleset.apply(&message_ref);
+        assert_eq!(outcome.redirects, vec!["elsewhere".to_string()]);
+        assert!(outcome.message.is_some());
+    }
+}
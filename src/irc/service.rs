@@ -0,0 +1,180 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::{Connection, Links, MessageRef, MessageStream, Reply, Ruleset, BUFFER_SIZE};
+use std::io::{ErrorKind, Read, Write};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::time::Duration;
+
+// How long a connection's owning thread may block in a single `read`
+// before it wakes up to flush anything another thread has queued for it
+// (a relay from `Links`, for instance). Bounds staleness on an idle link
+// without needing a non-blocking event loop.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Clone)]
+pub struct Service {
+    ruleset: Arc<Ruleset>,
+    links: Arc<Links>,
+}
+
+impl Service {
+    // Owns `connection` for its entire lifetime: only this thread ever
+    // reads or writes its socket directly, so relays from other threads
+    // (via the `outbox`/`inbox` channel registered with `Links`) never
+    // have to wait on a lock held across a blocking read.
+    pub fn handle_connection(&self, connection: Connection) {
+        if connection.set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+            return;
+        }
+
+        let mut connection = connection;
+        let mut framer = MessageStream::new();
+        let mut buffer = [0u8; BUFFER_SIZE];
+        let mut pending_password: Option<String> = None;
+        let mut linked_as: Option<String> = None;
+        let (outbox, inbox) = mpsc::channel();
+
+        loop {
+            match connection.stream_mut().read(&mut buffer) {
+                Ok(0) => return,
+                Ok(read) => {
+                    let batch = match framer.push(&buffer[..read]) {
+                        Ok(batch) => batch,
+                        Err(_e) => return,
+                    };
+
+                    for message_ref in batch.iter() {
+                        match message_ref.command() {
+                            "PASS" => {
+                                pending_password = message_ref.parameter(0).map(str::to_string);
+                                continue;
+                            }
+                            "SERVER" => {
+                                if let Some(server_name) = message_ref.parameter(0) {
+                                    if self.links.authenticate(pending_password.as_deref()) {
+                                        self.links
+                                            .register(server_name.to_string(), outbox.clone());
+                                        let already_linked = linked_as.is_some();
+                                        linked_as = Some(server_name.to_string());
+
+                                        // Greet only a peer that has just linked in, so a
+                                        // greeting sent back in reply is not answered again.
+                                        if already_linked {
+                                            continue;
+                                        }
+                                        let (pass, server) = self.links.greeting();
+                                        let greeting = pass.string() + &server.string();
+                                        if connection
+                                            .stream_mut()
+                                            .write_all(greeting.as_bytes())
+                                            .is_err()
+                                        {
+                                            return;
+                                        }
+                                    }
+                                }
+                                continue;
+                            }
+                            _ => {}
+                        }
+
+                        if let Some(from) = &linked_as {
+                            self.links.relay(&message_ref.to_owned(), from);
+                            continue;
+                        }
+
+                        let lines = self
+                            .handle_message(&message_ref)
+                            .strings()
+                            .unwrap_or_default();
+                        for line in lines {
+                            if connection.stream_mut().write_all(line.as_bytes()).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {}
+                Err(_e) => return,
+            }
+
+            while let Ok(message) = inbox.try_recv() {
+                if connection
+                    .stream_mut()
+                    .write_all(message.string().as_bytes())
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+    }
+
+    // Runs the ruleset over `message_ref` before any further dispatch.
+    // Rules can rewrite the message in place, inject synthesized replies,
+    // or drop it outright; dropped messages never reach dispatch at all.
+    // Messages arriving over a registered server link are relayed across
+    // the mesh instead (see `handle_connection`), bypassing this entirely.
+    fn handle_message(&self, message_ref: &MessageRef) -> Reply {
+        let outcome = self.ruleset.apply(message_ref);
+        let mut reply = Reply::new();
+
+        for message in outcome.replies {
+            reply.add_message(message);
+        }
+
+        if let Some(message) = &outcome.message {
+            for target in &outcome.redirects {
+                self.links.send_to(target, message);
+            }
+        }
+
+        if outcome.keep {
+            if let Some(message) = outcome.message {
+                // Command dispatch is not yet implemented, so the
+                // rewritten message is simply relayed on to any linked
+                // peers.
+                self.links.originate(message);
+            }
+        }
+
+        reply
+    }
+
+    pub fn new() -> Service {
+        Service {
+            ruleset: Arc::new(Ruleset::new()),
+            links: Arc::new(Links::new(String::new(), String::new())),
+        }
+    }
+
+    pub fn with_links(self, links: Links) -> Service {
+        Service {
+            links: Arc::new(links),
+            ..self
+        }
+    }
+
+    pub fn with_ruleset(self, ruleset: Ruleset) -> Service {
+        Service {
+            ruleset: Arc::new(ruleset),
+            ..self
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::{MessageRef, BUFFER_SIZE};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::str::from_utf8;
+
+// Frames raw socket reads into complete lines. A single read can contain
+// several messages plus a trailing fragment, and a message can be split
+// across reads, so incoming chunks are kept in a rope until a line
+// terminator is found rather than being parsed eagerly.
+pub struct MessageStream {
+    chunks: VecDeque<Vec<u8>>,
+    len: usize,
+}
+
+impl MessageStream {
+    // Appends a raw read to the buffer and returns a `ParsedBatch` lending
+    // out every line completed by it as a borrowed `MessageRef`, leaving
+    // any unterminated tail for the next read. Rejects the buffer once an
+    // unterminated tail exceeds the IRC 512-byte line limit, so a peer
+    // cannot grow it without bound.
+    pub fn push(&mut self, data: &[u8]) -> Result<ParsedBatch, ErrorKind> {
+        self.chunks.push_back(data.to_vec());
+        self.len += data.len();
+
+        let buffer = self.concat();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+
+        while i < buffer.len() {
+            if buffer[i] == b'\r' || buffer[i] == b'\n' {
+                if i > start {
+                    lines.push((start, i));
+                }
+                if buffer[i] == b'\r' && buffer.get(i + 1) == Some(&b'\n') {
+                    i += 1;
+                }
+                start = i + 1;
+            }
+            i += 1;
+        }
+
+        let tail = &buffer[start..];
+        if tail.len() > BUFFER_SIZE {
+            self.reset();
+            return Err(ErrorKind::InvalidData);
+        }
+
+        self.chunks.clear();
+        if !tail.is_empty() {
+            self.chunks.push_back(tail.to_vec());
+        }
+        self.len = tail.len();
+
+        Ok(ParsedBatch { buffer, lines })
+    }
+
+    fn concat(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.len);
+        for chunk in &self.chunks {
+            buffer.extend_from_slice(chunk);
+        }
+        buffer
+    }
+
+    fn reset(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    pub fn new() -> MessageStream {
+        MessageStream {
+            chunks: VecDeque::new(),
+            len: 0,
+        }
+    }
+}
+
+// The lines completed by one `MessageStream::push`, still backed by the
+// concatenated read buffer rather than copied out into owned `Message`s.
+// Call `iter` to parse each line into a `MessageRef` borrowing from that
+// buffer; materialize an owned `Message` only where ownership genuinely
+// needs to cross a thread or outlive the batch.
+pub struct ParsedBatch {
+    buffer: Vec<u8>,
+    lines: Vec<(usize, usize)>,
+}
+
+impl ParsedBatch {
+    pub fn iter(&self) -> impl Iterator<Item = MessageRef<'_>> {
+        self.lines.iter().filter_map(move |&(start, end)| {
+            from_utf8(&self.buffer[start..end])
+                .ok()
+                .and_then(|line| MessageRef::from_str(line).ok())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commands(stream: &mut MessageStream, data: &[u8]) -> Vec<String> {
+        stream
+            .push(data)
+            .unwrap()
+            .iter()
+            .map(|message_ref| message_ref.command().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_single_complete_line() {
+        let mut stream = MessageStream::new();
+        assert_eq!(commands(&mut stream, b"PING :1\r\n"), vec!["PING"]);
+    }
+
+    #[test]
+    fn parses_several_lines_from_one_read() {
+        let mut stream = MessageStream::new();
+        assert_eq!(
+            commands(&mut stream, b"PING :1\r\nPONG :1\r\n"),
+            vec!["PING", "PONG"]
+        );
+    }
+
+    #[test]
+    fn holds_an_unterminated_tail_for_the_next_read() {
+        let mut stream = MessageStream::new();
+        assert_eq!(commands(&mut stream, b"PI"), Vec::<String>::new());
+        assert_eq!(commands(&mut stream, b"NG :1\r\n"), vec!["PING"]);
+    }
+
+    #[test]
+    fn accepts_a_lone_lf_as_a_line_terminator() {
+        let mut stream = MessageStream::new();
+        assert_eq!(commands(&mut stream, b"PING :1\n"), vec!["PING"]);
+    }
+
+    #[test]
+    fn rejects_an_unterminated_tail_over_the_line_limit() {
+        let mut stream = MessageStream::new();
+        let oversized = vec![b'a'; BUFFER_SIZE + 1];
+        match stream.push(&oversized) {
+            Err(error) => assert_eq!(error, ErrorKind::InvalidData),
+            Ok(_) => panic!("expected an InvalidData error"),
+        }
+    }
+}
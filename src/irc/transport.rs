@@ -0,0 +1,109 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use std::io::{Read, Result, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// Wraps either a plaintext socket or a TLS session over one, so `Connection`
+// can stay transport-agnostic. `rustls::StreamOwned` performs the handshake
+// transparently on the first `read`/`write`, so callers never see it.
+pub enum Stream {
+    Plain(TcpStream),
+    Tls(Box<rustls::StreamOwned<rustls::ServerSession, TcpStream>>),
+}
+
+impl Stream {
+    pub fn is_encrypted(&self) -> bool {
+        match self {
+            Stream::Plain(_) => false,
+            Stream::Tls(_) => true,
+        }
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        match self {
+            Stream::Plain(tcp_stream) => tcp_stream.peer_addr(),
+            Stream::Tls(tls_stream) => tls_stream.sock.peer_addr(),
+        }
+    }
+
+    // Bounds how long a single `read` may block, so a connection's owning
+    // thread periodically wakes up even on an idle link to flush anything
+    // queued for it rather than blocking indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        match self {
+            Stream::Plain(tcp_stream) => tcp_stream.set_read_timeout(timeout),
+            Stream::Tls(tls_stream) => tls_stream.sock.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buffer: &mut [u8]) -> Result<usize> {
+        match self {
+            Stream::Plain(tcp_stream) => tcp_stream.read(buffer),
+            Stream::Tls(tls_stream) => tls_stream.read(buffer),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buffer: &[u8]) -> Result<usize> {
+        match self {
+            Stream::Plain(tcp_stream) => tcp_stream.write(buffer),
+            Stream::Tls(tls_stream) => tls_stream.write(buffer),
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self {
+            Stream::Plain(tcp_stream) => tcp_stream.flush(),
+            Stream::Tls(tls_stream) => tls_stream.flush(),
+        }
+    }
+}
+
+// Where to bind the TLS listener and which PEM-encoded identity to present.
+#[derive(Clone)]
+pub struct TlsConfig {
+    bind_string: String,
+    certificate_path: String,
+    private_key_path: String,
+}
+
+impl TlsConfig {
+    pub fn bind_string(&self) -> &String {
+        &self.bind_string
+    }
+
+    pub fn certificate_path(&self) -> &String {
+        &self.certificate_path
+    }
+
+    pub fn private_key_path(&self) -> &String {
+        &self.private_key_path
+    }
+
+    pub fn new(bind_string: String, certificate_path: String, private_key_path: String) -> TlsConfig {
+        TlsConfig {
+            bind_string,
+            certificate_path,
+            private_key_path,
+        }
+    }
+}
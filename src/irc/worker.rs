@@ -0,0 +1,50 @@
+// Copyright 2020 Jonathan Windle
+
+// This file is part of Platform.
+
+// Platform is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Platform is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with Platform.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::irc::{Connection, Service};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+pub struct Worker {
+    request_queue: Arc<Mutex<VecDeque<Connection>>>,
+    service: Service,
+}
+
+impl Worker {
+    pub fn new(request_queue: Arc<Mutex<VecDeque<Connection>>>, service: Service) -> Worker {
+        Worker {
+            request_queue,
+            service,
+        }
+    }
+
+    pub fn run(&self) -> JoinHandle<()> {
+        let request_queue = self.request_queue.clone();
+        let service = self.service.clone();
+
+        thread::spawn(move || loop {
+            let connection = request_queue.lock().unwrap().pop_front();
+            match connection {
+                Some(connection) => service.handle_connection(connection),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        })
+    }
+}
@@ -15,18 +15,61 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with Platform.  If not, see <https://www.gnu.org/licenses/>.
 
+// Connection::id/is_encrypted/stream and Stream::is_encrypted/peer_addr
+// are part of the irc types' public surface but have no caller yet;
+// allowed crate-wide rather than peppering every one individually.
+#![allow(dead_code)]
+
 extern crate num_cpus;
 
 mod irc;
 
+use std::fs;
+
 fn main() {
     let mut listener = irc::Listener::new();
-    let service = irc::Service::new();
+    let mut service = irc::Service::new();
+    if let Ok(script) = fs::read_to_string("rules.conf") {
+        if let Ok(ruleset) = irc::Ruleset::parse(&script) {
+            service = service.with_ruleset(ruleset);
+        }
+    }
+    if let Ok(contents) = fs::read_to_string("links.conf") {
+        let mut lines = contents.lines();
+        if let (Some(server_name), Some(link_password)) = (lines.next(), lines.next()) {
+            service = service.with_links(irc::Links::new(
+                server_name.to_string(),
+                link_password.to_string(),
+            ));
+        }
+    }
     for _ in 0..num_cpus::get() {
         let worker = irc::Worker::new(listener.clone_request_queue(), service.clone());
         let _ = worker.run();
     }
     listener.set_bind_string("127.0.0.1:6667".to_string());
-    let t = listener.run();
-    let _ = t.join();
+    listener.set_tls_config(irc::TlsConfig::new(
+        "127.0.0.1:6697".to_string(),
+        "cert.pem".to_string(),
+        "key.pem".to_string(),
+    ));
+
+    // Dials out to any configured peers, one "<address> <password>
+    // <server_name>" line per peer. The accepting side's handshake is
+    // reciprocal (see Links::greeting), so once the peer authenticates us
+    // it registers this link on its own end too.
+    if let Ok(contents) = fs::read_to_string("peers.conf") {
+        for line in contents.lines() {
+            let mut parts = line.splitn(3, ' ');
+            if let (Some(address), Some(password), Some(server_name)) =
+                (parts.next(), parts.next(), parts.next())
+            {
+                let _ = listener.link(address, password, server_name);
+            }
+        }
+    }
+
+    for handle in listener.run() {
+        let _ = handle.join();
+    }
 }